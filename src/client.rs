@@ -1,75 +1,406 @@
-use anyhow::Result;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use anyhow::{Context, Result};
+use rust_chat_app::config::Config;
+use std::fs::File;
+use std::io::BufReader as StdBufReader;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{split, AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
 use tokio::net::TcpStream;
 use tokio::sync::mpsc;
 use tokio::io;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::rustls::{self, OwnedTrustAnchor, RootCertStore};
+use tokio_rustls::TlsConnector;
+
+/// Default bound before `--max-retries` attempts are exhausted.
+const DEFAULT_MAX_RETRIES: u32 = 5;
+/// Default base delay for `--base-delay-ms`, doubled on every attempt.
+const DEFAULT_BASE_DELAY_MS: u64 = 250;
+/// Upper bound on the backoff delay, regardless of how many attempts fail.
+const MAX_BACKOFF: Duration = Duration::from_secs(10);
+
+/// Which transport to dial: a TCP address, a Unix domain socket path, or
+/// (on Windows) a named pipe path.
+enum Target {
+    Tcp(String),
+    #[cfg(unix)]
+    Unix(String),
+    #[cfg(windows)]
+    Pipe(String),
+}
+
+/// Command-line options for the client. Plaintext TCP remains the default;
+/// `--tls` opts into wrapping the connection in a `TlsStream`, and
+/// `--unix`/`--pipe` switch the transport away from TCP. `--tls` applies
+/// only to TCP; a `--unix` or `--pipe` connection is always plaintext.
+/// `--addr`, `--unix` and `--pipe` override the target read from
+/// `config.toml`.
+struct Args {
+    target_override: Option<Target>,
+    config_path: PathBuf,
+    tls: bool,
+    ca_cert: Option<PathBuf>,
+    server_name: String,
+    max_retries: u32,
+    base_delay: Duration,
+    nick: String,
+}
+
+fn parse_args() -> Args {
+    let mut target_override = None;
+    let mut config_path = PathBuf::from("config.toml");
+    let mut tls = false;
+    let mut ca_cert = None;
+    let mut server_name = "localhost".to_string();
+    let mut max_retries = DEFAULT_MAX_RETRIES;
+    let mut base_delay = Duration::from_millis(DEFAULT_BASE_DELAY_MS);
+    let mut nick = "guest".to_string();
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--addr" => {
+                if let Some(v) = args.next() {
+                    target_override = Some(Target::Tcp(v));
+                }
+            }
+            #[cfg(unix)]
+            "--unix" => {
+                if let Some(v) = args.next() {
+                    target_override = Some(Target::Unix(v));
+                }
+            }
+            #[cfg(windows)]
+            "--pipe" => {
+                if let Some(v) = args.next() {
+                    target_override = Some(Target::Pipe(v));
+                }
+            }
+            "--config" => {
+                if let Some(v) = args.next() {
+                    config_path = PathBuf::from(v);
+                }
+            }
+            "--tls" => tls = true,
+            "--ca-cert" => {
+                if let Some(v) = args.next() {
+                    ca_cert = Some(PathBuf::from(v));
+                }
+            }
+            "--server-name" => {
+                if let Some(v) = args.next() {
+                    server_name = v;
+                }
+            }
+            "--max-retries" => {
+                if let Some(v) = args.next().and_then(|v| v.parse().ok()) {
+                    max_retries = v;
+                }
+            }
+            "--base-delay-ms" => {
+                if let Some(v) = args.next().and_then(|v| v.parse().ok()) {
+                    base_delay = Duration::from_millis(v);
+                }
+            }
+            "--nick" => {
+                if let Some(v) = args.next() {
+                    nick = v;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Args {
+        target_override,
+        config_path,
+        tls,
+        ca_cert,
+        server_name,
+        max_retries,
+        base_delay,
+        nick,
+    }
+}
+
+/// Computes the delay before the next reconnect attempt: the base delay
+/// doubled once per prior attempt (at most 8 doublings), capped at
+/// [`MAX_BACKOFF`], plus a little jitter so many clients reconnecting at
+/// once don't all retry in lockstep.
+fn backoff_delay(attempt: u32, base: Duration) -> Duration {
+    let factor = 1u64 << attempt.min(8);
+    let delay_ms = (base.as_millis() as u64)
+        .saturating_mul(factor)
+        .min(MAX_BACKOFF.as_millis() as u64);
+    Duration::from_millis(delay_ms + jitter_ms(100))
+}
+
+fn jitter_ms(max: u64) -> u64 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0);
+    u64::from(nanos) % (max + 1)
+}
+
+/// Builds a `TlsConnector` whose root store is either loaded from a
+/// supplied CA certificate or falls back to the platform's native roots.
+fn build_tls_connector(ca_cert: &Option<PathBuf>) -> Result<TlsConnector> {
+    let mut roots = RootCertStore::empty();
+
+    match ca_cert {
+        Some(path) => {
+            let mut reader =
+                StdBufReader::new(File::open(path).context("opening CA certificate file")?);
+            for cert in rustls_pemfile::certs(&mut reader).context("parsing CA certificate")? {
+                roots
+                    .add(&rustls::Certificate(cert))
+                    .context("adding CA certificate to root store")?;
+            }
+        }
+        None => {
+            roots.add_trust_anchors(rustls_native_certs::load_native_certs()
+                .context("loading native root certificates")?
+                .into_iter()
+                .filter_map(|cert| {
+                    // A handful of platforms' native stores are known to
+                    // contain roots `webpki` can't parse as DER; skip
+                    // those rather than taking down the whole client.
+                    match webpki::TrustAnchor::try_from_cert_der(&cert.0) {
+                        Ok(ta) => Some(OwnedTrustAnchor::from_subject_spki_name_constraints(
+                            ta.subject,
+                            ta.spki,
+                            ta.name_constraints,
+                        )),
+                        Err(e) => {
+                            eprintln!("Skipping unparsable native root certificate: {}", e);
+                            None
+                        }
+                    }
+                }));
+        }
+    }
+
+    let config = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+
+    Ok(TlsConnector::from(Arc::new(config)))
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let stream = TcpStream::connect("127.0.0.1:8080").await?;
-    println!("Connected to server");
+    let args = parse_args();
+    let config = Config::load(&args.config_path)?;
 
-    let (reader, mut writer) = stream.into_split();
-    let mut reader = BufReader::new(reader);
     let (tx, mut rx) = mpsc::channel::<String>(10);
+    spawn_stdin_reader(tx);
 
-    // Spawn task to read user input
-    tokio::spawn({
-        let tx = tx.clone();
-        async move {
-            let stdin = io::stdin();
-            let mut stdin = BufReader::new(stdin);
-            let mut input = String::new();
-
-            loop {
-                input.clear();
-                match stdin.read_line(&mut input).await {
-                    Ok(0) => break, // EOF
-                    Ok(_) => {
-                        if input.trim().is_empty() {
-                            continue;
-                        }
-                        if tx.send(input.clone()).await.is_err() {
-                            break;
-                        }
+    let target = args
+        .target_override
+        .unwrap_or_else(|| Target::Tcp(config.addr()));
+
+    match target {
+        // Reconnection only makes sense for TCP: it's the transport that
+        // can legitimately drop and come back (server restarts, flaky
+        // networks). A Unix socket or named pipe disappearing means the
+        // server process itself is gone, so there's nothing to retry.
+        Target::Tcp(addr) => {
+            run_with_reconnect(
+                addr,
+                args.tls,
+                args.ca_cert,
+                args.server_name,
+                args.max_retries,
+                args.base_delay,
+                &args.nick,
+                &mut rx,
+            )
+            .await
+        }
+        #[cfg(unix)]
+        Target::Unix(path) => {
+            let stream = tokio::net::UnixStream::connect(&path).await?;
+            println!("Connected to server");
+            run_session(stream, &args.nick, &mut rx).await?;
+            Ok(())
+        }
+        #[cfg(windows)]
+        Target::Pipe(path) => {
+            let stream = tokio::net::windows::named_pipe::ClientOptions::new().open(&path)?;
+            println!("Connected to server");
+            run_session(stream, &args.nick, &mut rx).await?;
+            Ok(())
+        }
+    }
+}
+
+/// Spawns the task that reads lines from stdin and forwards them over
+/// `tx`. This task outlives any individual connection attempt so queued
+/// input (and the channel itself) survives a reconnect.
+fn spawn_stdin_reader(tx: mpsc::Sender<String>) {
+    tokio::spawn(async move {
+        let stdin = io::stdin();
+        let mut stdin = BufReader::new(stdin);
+        let mut input = String::new();
+
+        loop {
+            input.clear();
+            match stdin.read_line(&mut input).await {
+                Ok(0) => break, // EOF
+                Ok(_) => {
+                    if input.trim().is_empty() {
+                        continue;
                     }
-                    Err(e) => {
-                        eprintln!("Failed to read from stdin: {}", e);
+                    if tx.send(input.clone()).await.is_err() {
                         break;
                     }
                 }
+                Err(e) => {
+                    eprintln!("Failed to read from stdin: {}", e);
+                    break;
+                }
             }
         }
     });
+}
 
-    // Spawn task to send messages to the server
-    tokio::spawn(async move {
-        while let Some(msg) = rx.recv().await {
-            if let Err(e) = writer.write_all(msg.as_bytes()).await {
-                eprintln!("Failed to write to server: {}", e);
-                break;
+async fn connect_tls(
+    addr: &str,
+    ca_cert: &Option<PathBuf>,
+    server_name: &str,
+) -> Result<TlsStream<TcpStream>> {
+    let connector = build_tls_connector(ca_cert)?;
+    let tcp = TcpStream::connect(addr).await?;
+    let name =
+        rustls::ServerName::try_from(server_name).context("invalid TLS server name")?;
+    Ok(connector.connect(name, tcp).await?)
+}
+
+/// Connects to `addr` and runs sessions against it, reconnecting with
+/// exponential backoff whenever the connection attempt fails or the
+/// server closes an established connection unexpectedly. A user-initiated
+/// `/quit` is not treated as a drop to recover from — it ends the loop
+/// right away. Otherwise gives up after `max_retries` consecutive failed
+/// reconnects.
+async fn run_with_reconnect(
+    addr: String,
+    tls: bool,
+    ca_cert: Option<PathBuf>,
+    server_name: String,
+    max_retries: u32,
+    base_delay: Duration,
+    nick: &str,
+    rx: &mut mpsc::Receiver<String>,
+) -> Result<()> {
+    let mut attempt: u32 = 0;
+
+    loop {
+        let result = if tls {
+            match connect_tls(&addr, &ca_cert, &server_name).await {
+                Ok(stream) => {
+                    println!("Connected to server (TLS)");
+                    attempt = 0;
+                    run_session(stream, nick, rx).await
+                }
+                Err(e) => Err(e),
             }
-            if let Err(e) = writer.flush().await {
-                eprintln!("Failed to flush to server: {}", e);
-                break;
+        } else {
+            match TcpStream::connect(&addr).await {
+                Ok(stream) => {
+                    println!("Connected to server");
+                    attempt = 0;
+                    run_session(stream, nick, rx).await
+                }
+                Err(e) => Err(e.into()),
             }
-        }
-    });
+        };
 
-    // Read responses from server
-    loop {
-        let mut buffer = String::new();
-        let bytes_read = reader.read_line(&mut buffer).await?;
+        match result {
+            Ok(Disconnect::Intentional) => return Ok(()),
+            Ok(Disconnect::Unexpected) => {}
+            Err(e) => eprintln!("Connection error: {}", e),
+        }
 
-        if bytes_read == 0 {
-            println!("Server closed the connection.");
-            break;
+        attempt += 1;
+        if attempt > max_retries {
+            println!("Giving up after {} reconnect attempts", max_retries);
+            return Ok(());
         }
 
-        print!("{}", buffer);
+        let delay = backoff_delay(attempt - 1, base_delay);
+        println!(
+            "Reconnecting in {:?} (attempt {}/{})...",
+            delay, attempt, max_retries
+        );
+        tokio::time::sleep(delay).await;
     }
+}
 
-    Ok(())
+/// Why a connection's session ended, so the caller can tell a user's
+/// `/quit` apart from the server hanging up or a write failing.
+enum Disconnect {
+    /// The user sent `/quit`; don't try to reconnect.
+    Intentional,
+    /// The server closed the connection, or a write to it failed.
+    Unexpected,
+}
+
+/// Runs one connection's worth of the chat session: forwards queued stdin
+/// lines to the server and prints whatever the server sends back. Returns
+/// once the session ends, so the caller can decide whether to reconnect.
+async fn run_session<S>(
+    stream: S,
+    nick: &str,
+    rx: &mut mpsc::Receiver<String>,
+) -> Result<Disconnect>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (reader, mut writer) = split(stream);
+    let mut reader = BufReader::new(reader);
+    let mut stdin_open = true;
+
+    // Handshake: tell the server who we are before anything else.
+    writer
+        .write_all(format!("/nick {}\n", nick).as_bytes())
+        .await?;
+    writer.flush().await?;
+
+    loop {
+        let mut line = String::new();
+        tokio::select! {
+            maybe_msg = rx.recv(), if stdin_open => {
+                match maybe_msg {
+                    Some(msg) => {
+                        if let Err(e) = writer.write_all(msg.as_bytes()).await {
+                            eprintln!("Failed to write to server: {}", e);
+                            return Ok(Disconnect::Unexpected);
+                        }
+                        if let Err(e) = writer.flush().await {
+                            eprintln!("Failed to flush to server: {}", e);
+                            return Ok(Disconnect::Unexpected);
+                        }
+                        if msg.trim_end() == "/quit" {
+                            return Ok(Disconnect::Intentional);
+                        }
+                    }
+                    None => stdin_open = false, // stdin closed; keep receiving
+                }
+            }
+            result = reader.read_line(&mut line) => {
+                let bytes_read = result?;
+                if bytes_read == 0 {
+                    println!("Server closed the connection.");
+                    return Ok(Disconnect::Unexpected);
+                }
+                print!("{}", line);
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -195,4 +526,35 @@ mod tests {
         server.abort();
         Ok(())
     }
+
+    #[test]
+    fn backoff_delay_doubles_each_attempt_up_to_the_cap() {
+        let base = Duration::from_millis(100);
+        // Allow for the jitter's upper bound so the comparison isn't flaky.
+        let jitter_headroom = Duration::from_millis(100);
+
+        assert!(backoff_delay(0, base) >= base);
+        assert!(backoff_delay(0, base) <= base + jitter_headroom);
+
+        assert!(backoff_delay(1, base) >= base * 2);
+        assert!(backoff_delay(1, base) <= base * 2 + jitter_headroom);
+
+        assert!(backoff_delay(2, base) >= base * 4);
+    }
+
+    #[test]
+    fn backoff_delay_never_exceeds_max_backoff_plus_jitter() {
+        let base = Duration::from_millis(100);
+        for attempt in 0..32 {
+            let delay = backoff_delay(attempt, base);
+            assert!(delay <= MAX_BACKOFF + Duration::from_millis(100));
+        }
+    }
+
+    #[test]
+    fn jitter_ms_stays_within_bound() {
+        for _ in 0..50 {
+            assert!(jitter_ms(100) <= 100);
+        }
+    }
 }
@@ -0,0 +1,140 @@
+//! Runtime configuration loaded from a TOML file, with defaults for any
+//! field (or the file itself) that's missing. This is the place both
+//! binaries pull their host/port and other tunables from instead of
+//! having them baked in at compile time.
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::path::Path;
+
+#[derive(Debug, Deserialize)]
+pub struct Config {
+    #[serde(default)]
+    pub host: Host,
+    #[serde(default = "default_broadcast_capacity")]
+    pub broadcast_capacity: usize,
+    #[serde(default = "default_max_line_len")]
+    pub max_line_len: usize,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Host {
+    #[serde(default = "default_domain")]
+    pub domain: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+}
+
+impl Default for Host {
+    fn default() -> Self {
+        Host {
+            domain: default_domain(),
+            port: default_port(),
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            host: Host::default(),
+            broadcast_capacity: default_broadcast_capacity(),
+            max_line_len: default_max_line_len(),
+        }
+    }
+}
+
+fn default_domain() -> String {
+    "127.0.0.1".to_string()
+}
+
+fn default_port() -> u16 {
+    8080
+}
+
+fn default_broadcast_capacity() -> usize {
+    10
+}
+
+fn default_max_line_len() -> usize {
+    8192
+}
+
+impl Config {
+    /// Loads configuration from `path`. A missing file is not an error —
+    /// it just means every field falls back to its default — but a
+    /// present-and-malformed-or-invalid file is.
+    pub fn load(path: impl AsRef<Path>) -> Result<Config> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Config::default());
+        }
+
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("reading config file {}", path.display()))?;
+        let config: Config = toml::from_str(&contents)
+            .with_context(|| format!("parsing config file {}", path.display()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Rejects values that parse fine as TOML but would panic or otherwise
+    /// misbehave once used (e.g. `tokio::sync::broadcast::channel` panics
+    /// on a capacity of 0).
+    fn validate(&self) -> Result<()> {
+        if self.broadcast_capacity == 0 {
+            anyhow::bail!("broadcast_capacity must be at least 1");
+        }
+        if self.max_line_len == 0 {
+            anyhow::bail!("max_line_len must be at least 1");
+        }
+        Ok(())
+    }
+
+    /// The `host:port` address to bind or connect to, assembled from the
+    /// `[host]` table.
+    pub fn addr(&self) -> String {
+        format!("{}:{}", self.host.domain, self.host.port)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_missing_file_falls_back_to_defaults() {
+        let config = Config::load("does-not-exist.toml").unwrap();
+        assert_eq!(config.addr(), "127.0.0.1:8080");
+        assert_eq!(config.broadcast_capacity, 10);
+        assert_eq!(config.max_line_len, 8192);
+    }
+
+    #[test]
+    fn load_overrides_only_the_fields_present() {
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!("rust-chat-app-test-{}.toml", std::process::id()));
+        std::fs::write(&path, "[host]\nport = 9000\n").unwrap();
+
+        let config = Config::load(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(config.addr(), "127.0.0.1:9000");
+        assert_eq!(config.broadcast_capacity, 10);
+    }
+
+    #[test]
+    fn zero_broadcast_capacity_is_rejected() {
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!(
+            "rust-chat-app-test-zero-cap-{}.toml",
+            std::process::id()
+        ));
+        std::fs::write(&path, "broadcast_capacity = 0\n").unwrap();
+
+        let result = Config::load(&path);
+        let _ = std::fs::remove_file(&path);
+
+        assert!(result.is_err());
+    }
+}
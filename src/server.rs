@@ -1,67 +1,537 @@
-use anyhow::Result;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::net::{TcpListener, TcpStream};
-use tokio::sync::{broadcast, Mutex};
+use anyhow::{Context, Result};
+use rust_chat_app::config::Config;
+use rust_chat_app::transport::{BindTarget, Listener};
+use rustls_pemfile::{certs, pkcs8_private_keys};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader as StdBufReader;
+use std::path::PathBuf;
 use std::sync::Arc;
-use tracing::{info, error};
+use std::time::Duration;
+use tokio::io::{
+    split, AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt,
+    BufReader,
+};
+use tokio::sync::{broadcast, mpsc, watch, Mutex};
+use tokio::task::JoinSet;
+use tokio_rustls::rustls::{self, Certificate, PrivateKey};
+use tokio_rustls::TlsAcceptor;
+use tracing::{error, info};
 use tracing_subscriber;
 
+/// How long the server waits after broadcasting the shutdown notice for
+/// every client task to finish before giving up and exiting anyway. Must
+/// comfortably exceed [`CLIENT_WRITE_TIMEOUT`] plus the brief pause
+/// `handle_client` gives the shutdown notice to reach the client, or a
+/// perfectly healthy client gets cut off mid-drain.
+const SHUTDOWN_GRACE_PERIOD: Duration = Duration::from_secs(6);
+
+/// Depth of each client's outbound buffer. Bounding it means a client
+/// that can't keep up applies backpressure instead of growing without
+/// limit.
+const CLIENT_BUFFER_CAPACITY: usize = 32;
+
+/// How long a single write to a client may take before it's considered
+/// stuck and the connection is torn down.
+const CLIENT_WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A line destined for every subscriber of the broadcast channel.
+/// `origin` names the client that sent it (for chat lines and `/me`
+/// actions) or is `None` for server-generated notices; the forwarding
+/// task uses it to skip re-displaying a client's own message.
+#[derive(Clone)]
+struct Broadcast {
+    origin: Option<String>,
+    text: String,
+}
+
+impl Broadcast {
+    fn from_client(name: &str, text: String) -> Self {
+        Broadcast {
+            origin: Some(name.to_string()),
+            text,
+        }
+    }
+
+    fn notice(text: String) -> Self {
+        Broadcast { origin: None, text }
+    }
+}
+
+/// Registry of connected clients' nicknames, each paired with a sender
+/// the rest of the server can use to deliver a message to just that one
+/// client (e.g. a `/who` reply) without broadcasting it to everyone.
+type Clients = Arc<Mutex<HashMap<String, mpsc::Sender<String>>>>;
+
+/// Inserts `sender` under `base`, or under `base` suffixed with `-2`,
+/// `-3`, ... if `base` is already taken, so two connections never share
+/// (and can't clobber each other's) registry entry. Returns whichever
+/// name was actually used.
+async fn register_client(clients: &Clients, base: &str, sender: mpsc::Sender<String>) -> String {
+    let mut guard = clients.lock().await;
+    let mut name = base.to_string();
+    let mut suffix = 2;
+    while guard.contains_key(&name) {
+        name = format!("{}-{}", base, suffix);
+        suffix += 1;
+    }
+    guard.insert(name.clone(), sender);
+    name
+}
+
+/// What a client's line of input means, once slash-commands are peeled
+/// off a plain chat message.
+#[derive(Debug, PartialEq, Eq)]
+enum Command<'a> {
+    Quit,
+    Me(&'a str),
+    Who,
+    Say(&'a str),
+}
+
+fn parse_command(text: &str) -> Command<'_> {
+    if text == "/quit" {
+        Command::Quit
+    } else if let Some(action) = text.strip_prefix("/me ") {
+        Command::Me(action)
+    } else if text == "/who" {
+        Command::Who
+    } else {
+        Command::Say(text)
+    }
+}
+
+/// Outcome of reading one line through [`read_bounded_line`].
+enum LineRead {
+    /// The connection closed before a line arrived.
+    Eof,
+    /// A complete, in-bounds line is in the caller's buffer.
+    Line,
+    /// The line exceeded `max_len` with no newline in sight; the
+    /// remainder has been discarded so the next call starts fresh.
+    TooLong,
+}
+
+/// Reads one line, capping how much `reader` will buffer for it instead
+/// of trusting the client to eventually send a newline. Without this, an
+/// unterminated multi-gigabyte line would grow `line` without bound
+/// before `max_len` could ever be checked against the result.
+async fn read_bounded_line<R>(reader: &mut R, line: &mut String, max_len: usize) -> Result<LineRead>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let n = {
+        let mut limited = AsyncReadExt::take(&mut *reader, max_len as u64);
+        limited.read_line(line).await?
+    };
+
+    if n == 0 {
+        return Ok(LineRead::Eof);
+    }
+    // A line shorter than the cap with no newline was cut off by EOF, not
+    // by the cap; hand it over as-is and let the next call report `Eof`.
+    if line.ends_with('\n') || n < max_len {
+        return Ok(LineRead::Line);
+    }
+
+    // Hit the cap without a newline: drain the rest of this line so it
+    // doesn't get misread as the start of the next one.
+    let mut discard = Vec::new();
+    reader.read_until(b'\n', &mut discard).await?;
+    Ok(LineRead::TooLong)
+}
+
+/// Command-line options for the server. Plaintext TCP remains the default;
+/// `--tls` opts into wrapping each accepted socket in a `TlsStream`, and
+/// `--unix`/`--pipe` switch the listening transport away from TCP. `--addr`,
+/// `--unix` and `--pipe` override the address read from `config.toml`.
+struct Args {
+    tls: bool,
+    cert: PathBuf,
+    key: PathBuf,
+    config_path: PathBuf,
+    bind_override: Option<BindTarget>,
+}
+
+fn parse_args() -> Args {
+    let mut tls = false;
+    let mut cert = PathBuf::from("cert.pem");
+    let mut key = PathBuf::from("key.pem");
+    let mut config_path = PathBuf::from("config.toml");
+    let mut bind_override = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--tls" => tls = true,
+            "--cert" => {
+                if let Some(v) = args.next() {
+                    cert = PathBuf::from(v);
+                }
+            }
+            "--key" => {
+                if let Some(v) = args.next() {
+                    key = PathBuf::from(v);
+                }
+            }
+            "--config" => {
+                if let Some(v) = args.next() {
+                    config_path = PathBuf::from(v);
+                }
+            }
+            "--addr" => {
+                if let Some(v) = args.next() {
+                    bind_override = Some(BindTarget::Tcp(v));
+                }
+            }
+            #[cfg(unix)]
+            "--unix" => {
+                if let Some(v) = args.next() {
+                    bind_override = Some(BindTarget::Unix(v));
+                }
+            }
+            #[cfg(windows)]
+            "--pipe" => {
+                if let Some(v) = args.next() {
+                    bind_override = Some(BindTarget::Pipe(v));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Args {
+        tls,
+        cert,
+        key,
+        config_path,
+        bind_override,
+    }
+}
+
+/// Loads a certificate chain and private key from disk and builds a
+/// `TlsAcceptor` configured for no client authentication.
+fn load_tls_acceptor(cert_path: &PathBuf, key_path: &PathBuf) -> Result<TlsAcceptor> {
+    let mut cert_reader =
+        StdBufReader::new(File::open(cert_path).context("opening TLS certificate file")?);
+    let mut key_reader =
+        StdBufReader::new(File::open(key_path).context("opening TLS private key file")?);
+
+    let cert_chain: Vec<Certificate> = certs(&mut cert_reader)
+        .context("parsing TLS certificate chain")?
+        .into_iter()
+        .map(Certificate)
+        .collect();
+
+    let mut keys = pkcs8_private_keys(&mut key_reader).context("parsing TLS private key")?;
+    let key = PrivateKey(
+        keys.pop()
+            .context("no private key found in key file")?,
+    );
+
+    let config = rustls::ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(cert_chain, key)
+        .context("building TLS server config")?;
+
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     tracing_subscriber::fmt::init();
 
-    let listener = TcpListener::bind("127.0.0.1:8080").await?;
-    info!("Server running on 127.0.0.1:8080");
+    let args = parse_args();
+    let config = Config::load(&args.config_path)?;
+
+    let acceptor = if args.tls {
+        Some(load_tls_acceptor(&args.cert, &args.key)?)
+    } else {
+        None
+    };
+
+    let bind = args
+        .bind_override
+        .unwrap_or_else(|| BindTarget::Tcp(config.addr()));
+    let listener = Listener::bind(&bind).await?;
+    info!("Server listening (tls: {})", args.tls);
+
+    let (tx, _rx) = broadcast::channel::<Broadcast>(config.broadcast_capacity);
+    let clients: Clients = Arc::new(Mutex::new(HashMap::new()));
+
+    // A `watch` channel fans the shutdown signal out to every connected
+    // client's task; each holds its own clone of the receiver.
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            info!("Ctrl-C received, shutting down");
+            let _ = shutdown_tx.send(true);
+        }
+    });
 
-    let (tx, _rx) = broadcast::channel::<String>(10);
+    // Tracked (rather than bare `tokio::spawn`ed) so shutdown can wait for
+    // every client task to actually finish instead of guessing how long
+    // that takes.
+    let mut client_tasks = JoinSet::new();
 
     loop {
-        let (socket, addr) = listener.accept().await?;
-        info!("New client connected: {}", addr);
+        let mut accept_shutdown_rx = shutdown_rx.clone();
+        tokio::select! {
+            result = listener.accept() => {
+                let (socket, addr) = result?;
+                info!("New client connected: {}", addr);
 
-        let tx = tx.clone();
-        let rx = tx.subscribe();
+                let tx = tx.clone();
+                let rx = tx.subscribe();
+                let clients = Arc::clone(&clients);
+                let max_line_len = config.max_line_len;
+                let shutdown_rx = shutdown_rx.clone();
 
-        tokio::spawn(async move {
-            if let Err(e) = handle_client(socket, tx, rx).await {
-                error!("Error handling client {}: {}", addr, e);
+                if let Some(acceptor) = acceptor.clone() {
+                    client_tasks.spawn(async move {
+                        match acceptor.accept(socket).await {
+                            Ok(stream) => {
+                                if let Err(e) =
+                                    handle_client(stream, tx, rx, clients, max_line_len, shutdown_rx)
+                                        .await
+                                {
+                                    error!("Error handling client {}: {}", addr, e);
+                                }
+                            }
+                            Err(e) => error!("TLS handshake failed for {}: {}", addr, e),
+                        }
+                    });
+                } else {
+                    client_tasks.spawn(async move {
+                        if let Err(e) =
+                            handle_client(socket, tx, rx, clients, max_line_len, shutdown_rx).await
+                        {
+                            error!("Error handling client {}: {}", addr, e);
+                        }
+                    });
+                }
             }
-        });
+            _ = accept_shutdown_rx.changed() => {
+                if *accept_shutdown_rx.borrow() {
+                    break;
+                }
+            }
+        }
     }
+
+    let _ = tx.send(Broadcast::notice("* server shutting down\n".to_string()));
+    info!(
+        "Waiting up to {:?} for {} client task(s) to drain",
+        SHUTDOWN_GRACE_PERIOD,
+        client_tasks.len()
+    );
+    let drain = async {
+        while client_tasks.join_next().await.is_some() {}
+    };
+    if tokio::time::timeout(SHUTDOWN_GRACE_PERIOD, drain)
+        .await
+        .is_err()
+    {
+        info!("Shutdown grace period elapsed with client tasks still draining; exiting anyway");
+    }
+
+    Ok(())
 }
 
-async fn handle_client(
-    socket: TcpStream,
-    tx: broadcast::Sender<String>,
-    mut rx: broadcast::Receiver<String>,
-) -> Result<()> {
-    let (reader, writer) = socket.into_split();
-    let writer = Arc::new(Mutex::new(writer));
+async fn handle_client<S>(
+    socket: S,
+    tx: broadcast::Sender<Broadcast>,
+    mut rx: broadcast::Receiver<Broadcast>,
+    clients: Clients,
+    max_line_len: usize,
+    mut shutdown_rx: watch::Receiver<bool>,
+) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (reader, mut writer) = split(socket);
     let mut reader = BufReader::new(reader);
     let mut line = String::new();
 
-    // Task to forward broadcast messages to this client
-    let writer_clone = Arc::clone(&writer);
-    tokio::spawn(async move {
-        while let Ok(msg) = rx.recv().await {
-            let mut writer = writer_clone.lock().await;
-            if writer.write_all(msg.as_bytes()).await.is_err() {
-                break;
+    // The handshake: the client's very first line must be `/nick <name>`.
+    // Anything else (or a line over `max_line_len`) just falls back to a
+    // generic name rather than failing the connection; EOF before the
+    // handshake ends the connection.
+    line.clear();
+    let name = match read_bounded_line(&mut reader, &mut line, max_line_len).await? {
+        LineRead::Eof => return Ok(()),
+        LineRead::TooLong => "anonymous".to_string(),
+        LineRead::Line => line
+            .trim_end()
+            .strip_prefix("/nick ")
+            .map(|n| n.trim().to_string())
+            .filter(|n| !n.is_empty())
+            .unwrap_or_else(|| "anonymous".to_string()),
+    };
+
+    // Every outbound line for this client — broadcast relays, `/who`
+    // replies, and local notices alike — funnels through one bounded
+    // channel into the single task that owns the socket's write half.
+    // That keeps exactly one writer per connection (no `Mutex<writer>`
+    // to contend on) and gives the client's own backpressure a place to
+    // show up instead of blocking whoever is holding the writer.
+    let (out_tx, mut out_rx) = mpsc::channel::<String>(CLIENT_BUFFER_CAPACITY);
+    // Registering may rename a colliding nickname (e.g. two default
+    // "guest"s), so `name` from here on is whatever actually landed in
+    // the registry.
+    let name = register_client(&clients, &name, out_tx.clone()).await;
+    tx.send(Broadcast::notice(format!("* {} joined\n", name)))?;
+
+    let writer_handle = tokio::spawn(async move {
+        while let Some(text) = out_rx.recv().await {
+            match tokio::time::timeout(CLIENT_WRITE_TIMEOUT, writer.write_all(text.as_bytes()))
+                .await
+            {
+                Ok(Ok(())) => {}
+                // Either the write failed outright or the client didn't
+                // drain fast enough; either way, stop writing to it.
+                _ => break,
             }
         }
     });
 
-    // Read messages from this client and broadcast them
-    loop {
+    // Task relaying broadcast messages into this client's outbound
+    // buffer. A `Lagged(n)` means this client's `broadcast::Receiver`
+    // fell behind and the channel overwrote messages before it read
+    // them; we tell the client rather than silently losing its place.
+    let forward_name = name.clone();
+    let relay_out_tx = out_tx.clone();
+    let forward_handle = tokio::spawn(async move {
+        loop {
+            match rx.recv().await {
+                Ok(msg) => {
+                    if msg.origin.as_deref() == Some(forward_name.as_str()) {
+                        continue; // don't echo a client's own message back to it
+                    }
+                    if relay_out_tx.send(msg.text).await.is_err() {
+                        break;
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    let warning = format!("* you missed {} messages\n", n);
+                    if relay_out_tx.send(warning).await.is_err() {
+                        break;
+                    }
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    });
+
+    // Read messages from this client, interpreting slash-commands and
+    // broadcasting everything else. Also watches for a server shutdown
+    // so a client that isn't actively typing still unblocks promptly.
+    let mut shutting_down = false;
+    'read: loop {
         line.clear();
-        let bytes_read = reader.read_line(&mut line).await?;
-        if bytes_read == 0 {
-            break;
+        let outcome = tokio::select! {
+            result = read_bounded_line(&mut reader, &mut line, max_line_len) => result?,
+            // The writer task gave up on a failed or stuck write.
+            _ = out_tx.closed() => break 'read,
+            _ = shutdown_rx.changed() => {
+                if *shutdown_rx.borrow() {
+                    shutting_down = true;
+                    break 'read;
+                }
+                continue 'read;
+            }
+        };
+
+        match outcome {
+            LineRead::Eof => break,
+            LineRead::TooLong => {
+                let _ = out_tx
+                    .send("* line too long, message dropped\n".to_string())
+                    .await;
+                continue;
+            }
+            LineRead::Line => {}
+        }
+
+        let text = line.trim_end();
+        if text.is_empty() {
+            continue;
+        }
+
+        match parse_command(text) {
+            Command::Quit => break,
+            Command::Me(action) => {
+                info!("{} performed action: {}", name, action);
+                tx.send(Broadcast::from_client(
+                    &name,
+                    format!("* {} {}\n", name, action),
+                ))?;
+            }
+            Command::Who => {
+                let names: Vec<String> = clients.lock().await.keys().cloned().collect();
+                let _ = out_tx
+                    .send(format!("* users: {}\n", names.join(", ")))
+                    .await;
+            }
+            Command::Say(text) => {
+                info!("{}: {}", name, text);
+                tx.send(Broadcast::from_client(&name, format!("[{}] {}\n", name, text)))?;
+            }
         }
+    }
+
+    clients.lock().await.remove(&name);
+    let _ = tx.send(Broadcast::notice(format!("* {} left\n", name)));
 
-        info!("Received from client: {}", line.trim_end());
-        tx.send(line.clone())?;
+    if shutting_down {
+        // Give the forwarding task a moment to relay the server's own
+        // "shutting down" notice before we tear it down.
+        tokio::time::sleep(Duration::from_millis(200)).await;
     }
 
+    // Stop the relay task rather than leaking a detached one now that
+    // this client is done; dropping the last `out_tx` then lets the
+    // writer task drain whatever's buffered and exit on its own.
+    forward_handle.abort();
+    drop(out_tx);
+    let _ = writer_handle.await;
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_command_recognizes_slash_commands() {
+        assert_eq!(parse_command("/quit"), Command::Quit);
+        assert_eq!(parse_command("/me waves"), Command::Me("waves"));
+        assert_eq!(parse_command("/who"), Command::Who);
+        assert_eq!(parse_command("hello there"), Command::Say("hello there"));
+    }
+
+    #[test]
+    fn parse_command_does_not_treat_partial_prefixes_as_commands() {
+        // "/mention" starts with "/me" but isn't the "/me " command.
+        assert_eq!(parse_command("/mention bob"), Command::Say("/mention bob"));
+    }
+
+    #[tokio::test]
+    async fn register_client_suffixes_colliding_nicknames() {
+        let clients: Clients = Arc::new(Mutex::new(HashMap::new()));
+        let (tx1, _rx1) = mpsc::channel::<String>(1);
+        let (tx2, _rx2) = mpsc::channel::<String>(1);
+        let (tx3, _rx3) = mpsc::channel::<String>(1);
+
+        let first = register_client(&clients, "guest", tx1).await;
+        let second = register_client(&clients, "guest", tx2).await;
+        let third = register_client(&clients, "guest", tx3).await;
+
+        assert_eq!(first, "guest");
+        assert_eq!(second, "guest-2");
+        assert_eq!(third, "guest-3");
+        assert_eq!(clients.lock().await.len(), 3);
+    }
+}
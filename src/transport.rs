@@ -0,0 +1,142 @@
+//! Transport abstraction so the chat protocol can run over TCP, Unix
+//! domain sockets, or (on Windows) named pipes without `handle_client`
+//! caring which one backs a given connection. Every accepted connection
+//! is normalized to a single [`Stream`] that implements
+//! `AsyncRead + AsyncWrite`, so the rest of the server only ever deals
+//! with that one type.
+
+use anyhow::Result;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::{TcpListener, TcpStream};
+
+#[cfg(unix)]
+use tokio::net::{UnixListener, UnixStream};
+
+#[cfg(windows)]
+use tokio::net::windows::named_pipe::{NamedPipeServer, ServerOptions};
+
+/// Where the server should listen: a TCP address, a Unix domain socket
+/// path, or (on Windows) a named pipe path.
+pub enum BindTarget {
+    Tcp(String),
+    #[cfg(unix)]
+    Unix(String),
+    #[cfg(windows)]
+    Pipe(String),
+}
+
+/// A listener bound to one concrete transport that hands back a
+/// normalized [`Stream`] for every accepted connection.
+pub enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+    #[cfg(windows)]
+    Pipe(String),
+}
+
+impl Listener {
+    pub async fn bind(target: &BindTarget) -> Result<Self> {
+        match target {
+            BindTarget::Tcp(addr) => Ok(Listener::Tcp(TcpListener::bind(addr).await?)),
+            #[cfg(unix)]
+            BindTarget::Unix(path) => {
+                // A stale socket file from a previous run would otherwise
+                // make the bind fail with "address in use".
+                let _ = std::fs::remove_file(path);
+                Ok(Listener::Unix(UnixListener::bind(path)?))
+            }
+            #[cfg(windows)]
+            BindTarget::Pipe(path) => Ok(Listener::Pipe(path.clone())),
+        }
+    }
+
+    /// Accepts the next connection, returning the normalized stream and a
+    /// human-readable peer description for logging.
+    pub async fn accept(&self) -> Result<(Stream, String)> {
+        match self {
+            Listener::Tcp(listener) => {
+                let (socket, addr) = listener.accept().await?;
+                Ok((Stream::Tcp(socket), addr.to_string()))
+            }
+            #[cfg(unix)]
+            Listener::Unix(listener) => {
+                let (socket, _) = listener.accept().await?;
+                Ok((Stream::Unix(socket), "unix socket".to_string()))
+            }
+            #[cfg(windows)]
+            Listener::Pipe(path) => {
+                // A fresh pipe instance is created for each connection,
+                // mirroring how `TcpListener::accept` hands back a new
+                // socket per client.
+                let server = ServerOptions::new().create(path)?;
+                server.connect().await?;
+                Ok((Stream::Pipe(server), "named pipe".to_string()))
+            }
+        }
+    }
+}
+
+/// A connected duplex stream, regardless of which concrete transport
+/// produced it.
+pub enum Stream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+    #[cfg(windows)]
+    Pipe(NamedPipeServer),
+}
+
+impl AsyncRead for Stream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            Stream::Tcp(s) => Pin::new(s).poll_read(cx, buf),
+            #[cfg(unix)]
+            Stream::Unix(s) => Pin::new(s).poll_read(cx, buf),
+            #[cfg(windows)]
+            Stream::Pipe(s) => Pin::new(s).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for Stream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        match self.get_mut() {
+            Stream::Tcp(s) => Pin::new(s).poll_write(cx, buf),
+            #[cfg(unix)]
+            Stream::Unix(s) => Pin::new(s).poll_write(cx, buf),
+            #[cfg(windows)]
+            Stream::Pipe(s) => Pin::new(s).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            Stream::Tcp(s) => Pin::new(s).poll_flush(cx),
+            #[cfg(unix)]
+            Stream::Unix(s) => Pin::new(s).poll_flush(cx),
+            #[cfg(windows)]
+            Stream::Pipe(s) => Pin::new(s).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            Stream::Tcp(s) => Pin::new(s).poll_shutdown(cx),
+            #[cfg(unix)]
+            Stream::Unix(s) => Pin::new(s).poll_shutdown(cx),
+            #[cfg(windows)]
+            Stream::Pipe(s) => Pin::new(s).poll_shutdown(cx),
+        }
+    }
+}